@@ -1,223 +1,554 @@
-use std::fs::{self, File, remove_dir_all, remove_file};
-use std::io::{self, BufReader, BufWriter, Error, ErrorKind};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::{Component, Path, PathBuf};
-use std::str::FromStr;
 
-use age::{Encryptor, x25519};
+use age::x25519;
 use anyhow::{Context, Result, bail};
 use chrono::Local;
-use tar::Builder;
 use walkdir::WalkDir;
 
-pub fn backup_directory_contents(
-    source_path: impl AsRef<Path>,
+use crate::chunk_store::ChunkStore;
+use crate::chunker::ContentDefinedChunker;
+use crate::compression::{CompressionCodec, CompressionReader, CompressionWriter};
+use crate::manifest::{FileEntry, FileFingerprint, Manifest, list_manifests};
+
+/// The result of a [`prune_backups`] run.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PruneReport {
+    pub removed_manifests: usize,
+    pub removed_chunks: usize,
+}
+
+/// Tunables for a single [`create_chunked_backup`] run, grouped together so they can be
+/// threaded through [`chunk_source_into_store`] and [`chunk_and_store_file`] as one
+/// value instead of four separate parameters.
+pub struct BackupOptions {
+    /// age recipient (public key) to encrypt chunks and the manifest for.
+    pub public_key: String,
+    /// Re-chunk and re-store every file, bypassing the chunk store's dedup.
+    pub force: bool,
+    /// Compression codec to apply to chunks before encryption.
+    pub compression_codec: CompressionCodec,
+    /// Don't descend into directories on a different filesystem than their source path.
+    pub one_file_system: bool,
+}
+
+/// Chunks every source path with a content-defined chunker, stores each chunk
+/// (compressed, then individually age-encrypted) in the chunk store by its BLAKE3
+/// digest, and writes an encrypted manifest listing every file's metadata and ordered
+/// chunk digests. Chunks whose digest is already present in the store are not
+/// re-written, so a daily run only encrypts and stores new or changed data.
+///
+/// Unless `options.force` is set, existing chunks are left untouched rather than
+/// re-encrypted.
+///
+/// If `reference_identity` is given, the most recently written manifest is decrypted
+/// and consulted: a walked file whose size/mtime/ctime fingerprint matches that
+/// manifest's entry for the same path, and whose every referenced chunk is still
+/// present in the chunk store, is assumed unchanged, and its chunk digests are reused
+/// without re-opening, re-chunking, or re-hashing the file. Without a reference
+/// identity (or with `options.force` set), every file is always re-chunked.
+///
+/// If `options.one_file_system` is set, descent stops at any directory whose device id
+/// differs from that of its top-level source path, so mounted network shares, external
+/// drives, and pseudo-filesystems under a source directory are skipped rather than
+/// pulled in.
+pub fn create_chunked_backup(
+    source_paths: &[String],
     backup_folder_path: impl AsRef<Path>,
-) -> Result<()> {
-    let source_path: &Path = source_path.as_ref();
+    chunk_store_path: impl AsRef<Path>,
+    options: BackupOptions,
+    reference_identity: Option<&x25519::Identity>,
+) -> Result<PathBuf> {
     let backup_folder_path: &Path = backup_folder_path.as_ref();
+    let now = Local::now();
 
-    if !source_path.is_absolute() {
-        bail!("source path '{}' is not absolute", source_path.display());
-    }
+    let timestamp: String = now.format("%Y-%m-%d_%H-%M-%S").to_string();
+    let manifest_filename: String =
+        format!("{}-{}.manifest", backup_folder_path.display(), timestamp);
 
-    if !source_path.is_dir() {
-        return backup_file(source_path, backup_folder_path);
-    }
+    let chunk_store = ChunkStore::open(chunk_store_path)?;
+    let mut manifest = Manifest::new();
 
-    let full_backup_folder_path: PathBuf = build_full_backup_path(source_path, backup_folder_path);
+    let reference_manifest: Option<Manifest> = if options.force {
+        None
+    } else {
+        match reference_identity {
+            Some(identity) => load_most_recent_manifest(backup_folder_path, identity)?,
+            None => None,
+        }
+    };
 
-    fs::create_dir_all(&full_backup_folder_path)?;
+    for source_path in source_paths {
+        let source_path: &Path = Path::new(source_path);
 
-    for entry in fs::read_dir(&source_path)? {
-        copy_file_from_folder(entry?.path(), &full_backup_folder_path, backup_folder_path)?;
+        if !source_path.is_absolute() {
+            bail!("source path '{}' is not absolute", source_path.display());
+        }
+
+        chunk_source_into_store(source_path, &chunk_store, &mut manifest, reference_manifest.as_ref(), &options)
+            .with_context(|| format!("failed to back up {}", source_path.display()))?;
     }
 
-    return Ok(());
+    manifest
+        .save(&manifest_filename, &options.public_key)
+        .with_context(|| format!("failed to write manifest {manifest_filename}"))?;
+
+    return Ok(PathBuf::from(manifest_filename));
 }
 
-fn backup_file(source_path: impl AsRef<Path>, backup_folder_path: impl AsRef<Path>) -> Result<()> {
-    let source_path: &Path = source_path.as_ref();
-    let backup_folder_path: &Path = backup_folder_path.as_ref();
+/// Loads the most recently written manifest next to `backup_folder_path`, if any, so
+/// [`chunk_source_into_store`] can skip files whose fingerprint hasn't changed.
+fn load_most_recent_manifest(
+    backup_folder_path: &Path,
+    identity: &x25519::Identity,
+) -> Result<Option<Manifest>> {
+    let manifests: Vec<PathBuf> = list_manifests(backup_folder_path)?; // oldest first
 
-    if !source_path.is_file() {
-        bail!("path or file '{}' does not exist", source_path.display());
-    }
+    let Some(latest_manifest_path) = manifests.last() else {
+        return Ok(None);
+    };
 
-    let full_backup_folder_path: PathBuf = build_full_backup_path(source_path, backup_folder_path);
+    let manifest = Manifest::load(latest_manifest_path, identity).with_context(|| {
+        format!(
+            "failed to load reference manifest {}",
+            latest_manifest_path.display()
+        )
+    })?;
 
-    let Some(parent_dir) = full_backup_folder_path.parent() else {
-        bail!(
-            "internal error extracting parent from {}",
-            full_backup_folder_path.display()
-        );
+    return Ok(Some(manifest));
+}
+
+fn chunk_source_into_store(
+    source_path: &Path,
+    chunk_store: &ChunkStore,
+    manifest: &mut Manifest,
+    reference_manifest: Option<&Manifest>,
+    options: &BackupOptions,
+) -> Result<()> {
+    let root_device: Option<u64> = if options.one_file_system {
+        Some(
+            fs::metadata(source_path)
+                .with_context(|| format!("failed to stat {}", source_path.display()))?
+                .dev(),
+        )
+    } else {
+        None
     };
 
-    fs::create_dir_all(&parent_dir)?;
+    // Compared against the top-level source path's device rather than each directory's
+    // immediate parent: since a filesystem boundary can only be crossed once on the way
+    // down from a single-rooted walk, the two are equivalent for every source path that
+    // itself sits on one filesystem, and comparing against a fixed root avoids threading
+    // a running "parent device" value through `filter_entry`'s closure.
+    let walker = WalkDir::new(source_path).into_iter().filter_entry(move |entry| {
+        let Some(root_device) = root_device else {
+            return true;
+        };
+
+        if !entry.file_type().is_dir() {
+            return true;
+        }
+
+        match entry.metadata() {
+            Ok(metadata) if metadata.dev() == root_device => true,
+            Ok(_) => {
+                println!(
+                    "skipping mount point {}: different filesystem (--one-file-system)",
+                    entry.path().display()
+                );
+                false
+            }
+            Err(_) => true,
+        }
+    });
+
+    for entry in walker {
+        let entry = entry.with_context(|| format!("failed to walk {}", source_path.display()))?;
 
-    copy_file_from_folder(
-        source_path.to_path_buf(),
-        &parent_dir.to_path_buf(),
-        backup_folder_path,
-    )?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        if !entry.file_type().is_file() {
+            println!(
+                "skipping non-regular file {}: not a regular file",
+                entry.path().display()
+            );
+            continue;
+        }
+
+        let metadata = entry
+            .metadata()
+            .with_context(|| format!("failed to stat {}", entry.path().display()))?;
+        let fingerprint = FileFingerprint::from_metadata(&metadata);
+
+        let reusable_entry = reference_manifest
+            .and_then(|m| m.entry(entry.path()))
+            .filter(|reference_entry| reference_entry.fingerprint == fingerprint)
+            .filter(|reference_entry| {
+                reference_entry
+                    .chunk_digests
+                    .iter()
+                    .all(|digest_bytes| chunk_store.contains(&blake3::Hash::from(*digest_bytes)))
+            });
+
+        let chunk_digests: Vec<[u8; 32]> = match reusable_entry {
+            Some(reference_entry) => reference_entry.chunk_digests.clone(),
+            None => chunk_and_store_file(entry.path(), chunk_store, options)
+                .with_context(|| format!("failed to chunk {}", entry.path().display()))?,
+        };
+
+        manifest.record(
+            entry.path().to_path_buf(),
+            FileEntry {
+                fingerprint,
+                mode: metadata.mode(),
+                chunk_digests,
+            },
+        );
+    }
 
     return Ok(());
 }
 
-pub fn zip_files_in_folder(backup_folder_path: impl AsRef<Path>) -> Result<PathBuf> {
-    let folder: &Path = backup_folder_path.as_ref();
-    let now = Local::now();
+fn chunk_and_store_file(path: &Path, chunk_store: &ChunkStore, options: &BackupOptions) -> Result<Vec<[u8; 32]>> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut chunker = ContentDefinedChunker::new(BufReader::new(file));
+    let mut chunk_digests: Vec<[u8; 32]> = Vec::new();
 
-    let timestamp: String = now.format("%Y-%m-%d_%H-%M-%S").to_string();
-    let filename: String = format!("{}-{}.tar", folder.display(), timestamp);
+    while let Some(chunk) = chunker.next_chunk()? {
+        let digest = blake3::hash(&chunk);
 
-    let tar_file = match File::create(&filename) {
-        Ok(file) => file,
-        Err(_) => {
-            bail!("failed to backup file named {}", filename);
-        }
-    };
+        let mut compressor = CompressionWriter::new(options.compression_codec, Vec::new())?;
+        compressor.write_all(&chunk)?;
+        let compressed_chunk: Vec<u8> = compressor.finish()?;
 
-    let mut archive: Builder<File> = Builder::new(tar_file);
+        chunk_store.store(&digest, options.compression_codec, &compressed_chunk, &options.public_key, options.force)?;
+        chunk_digests.push(*digest.as_bytes());
+    }
 
-    archive
-        .append_dir_all(folder, folder)
-        .with_context(|| format!("failed to append directory {} to archive", folder.display()))?;
+    return Ok(chunk_digests);
+}
 
-    archive.finish()?;
+/// Decrypts a manifest and rebuilds every file it describes under `destination` by
+/// concatenating each file's chunks, in order, from the chunk store. Entries are
+/// keyed by their original absolute path; the leading [`RootDir`](Component::RootDir)
+/// is stripped so each is rejoined under `destination`, and any entry whose
+/// normalized path would escape `destination` is rejected so a malicious manifest
+/// can't write outside the restore root.
+///
+/// If `path_prefix` is set, only files whose original path starts with it are
+/// restored.
+pub fn restore_chunked_backup(
+    manifest_path: impl AsRef<Path>,
+    chunk_store_path: impl AsRef<Path>,
+    identity: &x25519::Identity,
+    destination: impl AsRef<Path>,
+    path_prefix: Option<&Path>,
+) -> Result<()> {
+    let manifest_path: &Path = manifest_path.as_ref();
+    let destination: &Path = destination.as_ref();
 
-    remove_dir_all(folder)
-        .with_context(|| format!("failed to remove backup directory {}", folder.display()))?;
+    fs::create_dir_all(destination)
+        .with_context(|| format!("failed to create destination {}", destination.display()))?;
 
-    return Ok(PathBuf::from(filename));
-}
+    let manifest = Manifest::load(manifest_path, identity)
+        .with_context(|| format!("failed to load manifest {}", manifest_path.display()))?;
+    let chunk_store = ChunkStore::open(chunk_store_path)?;
 
-pub fn encrypt_file<P: AsRef<Path>>(input_file_path: P, public_key: String) -> Result<()> {
-    let output_filename: PathBuf = input_file_path.as_ref().with_extension("tar.age");
+    for (file_path, entry) in manifest.entries() {
+        if let Some(prefix) = path_prefix {
+            if !file_path.starts_with(prefix) {
+                continue;
+            }
+        }
 
-    let recipient = x25519::Recipient::from_str(&public_key).map_err(|e| {
-        Error::new(
-            ErrorKind::InvalidInput,
-            format!("invalid age recipient \"{public_key}\": {e}"),
-        )
-    })?;
+        let safe_relative_path: PathBuf = sanitize_entry_path(&strip_root(file_path))?;
+        let output_path: PathBuf = destination.join(&safe_relative_path);
 
-    let encryptor = Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient))
-        .expect("recipient iterator is non-empty");
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {}", parent.display()))?;
+        }
+
+        let mut output_file = BufWriter::new(
+            File::create(&output_path)
+                .with_context(|| format!("failed to create {}", output_path.display()))?,
+        );
 
-    let mut input_file: BufReader<File> = BufReader::new(File::open(&input_file_path)?);
-    let output_file: BufWriter<File> = BufWriter::new(File::create(output_filename)?);
+        for digest_bytes in &entry.chunk_digests {
+            let digest = blake3::Hash::from(*digest_bytes);
+            let (codec, compressed_chunk) = chunk_store
+                .load(&digest, identity)
+                .with_context(|| format!("missing chunk for {}", file_path.display()))?;
 
-    let mut encrypted_output_file: age::stream::StreamWriter<BufWriter<File>> = encryptor
-        .wrap_output(output_file)
-        .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?;
+            let mut decompressed_chunk: Vec<u8> = Vec::new();
+            CompressionReader::new(codec, compressed_chunk.as_slice())?.read_to_end(&mut decompressed_chunk)?;
 
-    io::copy(&mut input_file, &mut encrypted_output_file)?;
-    encrypted_output_file.finish()?;
+            output_file.write_all(&decompressed_chunk)?;
+        }
 
-    remove_file(input_file_path)?;
+        output_file
+            .flush()
+            .with_context(|| format!("failed to restore {}", output_path.display()))?;
+
+        fs::set_permissions(&output_path, fs::Permissions::from_mode(entry.mode))
+            .with_context(|| format!("failed to set permissions on {}", output_path.display()))?;
+    }
 
     return Ok(());
 }
 
-fn build_full_backup_path(
-    source_path: impl AsRef<Path>,
+/// Deletes every manifest except the `keep_most_recent` newest, then garbage-collects
+/// any chunk no longer referenced by a remaining manifest.
+pub fn prune_backups(
     backup_folder_path: impl AsRef<Path>,
-) -> PathBuf {
-    let source_path = source_path.as_ref();
-    let backup_folder_path = backup_folder_path.as_ref();
-
-    let relative_source_path = source_path
-        .strip_prefix(Component::RootDir)
-        .unwrap_or(source_path);
+    chunk_store_path: impl AsRef<Path>,
+    identity: &x25519::Identity,
+    keep_most_recent: usize,
+) -> Result<PruneReport> {
+    let backup_folder_path: &Path = backup_folder_path.as_ref();
+    let manifests: Vec<PathBuf> = list_manifests(backup_folder_path)?; // oldest first
 
-    backup_folder_path.join(relative_source_path)
-}
+    let stale_count: usize = manifests.len().saturating_sub(keep_most_recent);
+    let (stale_manifests, remaining_manifests) = manifests.split_at(stale_count);
 
-fn copy_file_from_folder(
-    file: PathBuf,
-    destination_folder: &PathBuf,
-    backup_folder_path: impl AsRef<Path>,
-) -> Result<()> {
-    if file.is_dir() {
-        return backup_folder(file, backup_folder_path); // The "file" it is actually a folder in this context.
+    for manifest_path in stale_manifests {
+        fs::remove_file(manifest_path)
+            .with_context(|| format!("failed to remove manifest {}", manifest_path.display()))?;
     }
 
-    if !file.is_file() {
-        println!(
-            "skipping non-regular file {}: not a regular file",
-            file.display()
-        );
-        return Ok(());
+    let mut referenced_digests: HashSet<blake3::Hash> = HashSet::new();
+
+    for manifest_path in remaining_manifests {
+        let manifest = Manifest::load(manifest_path, identity)
+            .with_context(|| format!("failed to load manifest {}", manifest_path.display()))?;
+
+        for (_, entry) in manifest.entries() {
+            for digest_bytes in &entry.chunk_digests {
+                referenced_digests.insert(blake3::Hash::from(*digest_bytes));
+            }
+        }
     }
 
-    let mut file_destination: PathBuf = destination_folder.to_path_buf();
+    let chunk_store = ChunkStore::open(chunk_store_path)?;
+    let removed_chunks: usize = chunk_store.garbage_collect(&referenced_digests)?;
 
-    if let Some(file_name) = file.file_name() {
-        file_destination.push(file_name);
-    } else {
-        println!(
-            "skipping file {}: path has no final component",
-            file.display()
-        );
-        return Ok(());
-    };
+    return Ok(PruneReport {
+        removed_manifests: stale_manifests.len(),
+        removed_chunks,
+    });
+}
 
-    if let Err(err) = fs::copy(&file, &file_destination) {
-        eprintln!(
-            "failed to copy file {} â†’ {}: {}",
-            file.display(),
-            file_destination.display(),
-            err
-        );
+/// Strips the leading [`RootDir`](Component::RootDir) from an absolute manifest key so
+/// it can be rejoined relative to the restore destination.
+fn strip_root(path: &Path) -> PathBuf {
+    path.strip_prefix(Component::RootDir).unwrap_or(path).to_path_buf()
+}
+
+/// Rejects any `..`, absolute, or prefix component so a manifest entry can never
+/// resolve outside the directory it's being restored into.
+fn sanitize_entry_path(entry_path: &Path) -> Result<PathBuf> {
+    let mut sanitized = PathBuf::new();
+
+    for component in entry_path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                bail!(
+                    "manifest entry '{}' escapes the restore destination",
+                    entry_path.display()
+                );
+            }
+        }
     }
 
-    return Ok(());
+    return Ok(sanitized);
 }
 
-fn backup_folder(folder: PathBuf, backup_folder_path: impl AsRef<Path>) -> Result<()> {
-    if !folder.is_dir() {
-        bail!("source path '{}' is not a directory", folder.display());
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use age::x25519;
+
+    use super::*;
+
+    /// Each test gets its own directory under the system temp dir so concurrent test
+    /// runs can't collide.
+    fn temp_dir_root(label: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(format!("laptop-backup-{label}-test-{}-{n}", std::process::id()))
     }
 
-    let full_backup_folder_path: PathBuf =
-        create_folder_in_backup_structure(&folder, backup_folder_path.as_ref())?;
+    fn test_options(public_key: String) -> BackupOptions {
+        BackupOptions {
+            public_key,
+            force: false,
+            compression_codec: CompressionCodec::None,
+            one_file_system: false,
+        }
+    }
 
-    for file in WalkDir::new(&folder).max_depth(1) {
-        let file = file?;
-        if PathBuf::from(file.path()).is_dir() {
-            if file.path().canonicalize()? != folder.canonicalize()? {
-                backup_folder(file.path().to_path_buf(), backup_folder_path.as_ref())?;
-            }
-            continue;
+    #[test]
+    fn chunk_source_into_store_reuses_chunk_digests_for_an_unchanged_file() {
+        let identity = x25519::Identity::generate();
+        let public_key = identity.to_public().to_string();
+
+        let chunk_store = ChunkStore::open(temp_dir_root("chunk-store")).unwrap();
+        let source_dir = temp_dir_root("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        let file_path = source_dir.join("a.txt");
+        fs::write(&file_path, b"hello world").unwrap();
+
+        let options = test_options(public_key);
+
+        let mut first_manifest = Manifest::new();
+        chunk_source_into_store(&source_dir, &chunk_store, &mut first_manifest, None, &options).unwrap();
+
+        let mut second_manifest = Manifest::new();
+        chunk_source_into_store(
+            &source_dir,
+            &chunk_store,
+            &mut second_manifest,
+            Some(&first_manifest),
+            &options,
+        )
+        .unwrap();
+
+        let first_entry = first_manifest.entry(&file_path).unwrap();
+        let second_entry = second_manifest.entry(&file_path).unwrap();
+        assert_eq!(first_entry.chunk_digests, second_entry.chunk_digests);
+    }
+
+    #[test]
+    fn chunk_source_into_store_rechunks_when_a_referenced_chunk_went_missing() {
+        let identity = x25519::Identity::generate();
+        let public_key = identity.to_public().to_string();
+
+        let chunk_store = ChunkStore::open(temp_dir_root("chunk-store")).unwrap();
+        let source_dir = temp_dir_root("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        let file_path = source_dir.join("a.txt");
+        fs::write(&file_path, b"hello world").unwrap();
+
+        let options = test_options(public_key);
+
+        let mut first_manifest = Manifest::new();
+        chunk_source_into_store(&source_dir, &chunk_store, &mut first_manifest, None, &options).unwrap();
+
+        for digest_bytes in &first_manifest.entry(&file_path).unwrap().chunk_digests {
+            let digest = blake3::Hash::from(*digest_bytes);
+            assert!(chunk_store.contains(&digest));
         }
-        let entry_path = file.path().to_path_buf();
 
-        copy_file_from_folder(
-            entry_path.to_path_buf(),
-            &full_backup_folder_path,
-            backup_folder_path.as_ref(),
-        )?;
+        // A fresh, empty chunk store stands in for one a referenced chunk has gone
+        // missing from (e.g. an interrupted prune), without reaching into ChunkStore's
+        // private fanout layout to delete a file by hand.
+        let lost_digest = blake3::Hash::from(first_manifest.entry(&file_path).unwrap().chunk_digests[0]);
+        let lost_chunk_store = ChunkStore::open(temp_dir_root("chunk-store-without-one-chunk")).unwrap();
+
+        let mut second_manifest = Manifest::new();
+        chunk_source_into_store(
+            &source_dir,
+            &lost_chunk_store,
+            &mut second_manifest,
+            Some(&first_manifest),
+            &options,
+        )
+        .unwrap();
+
+        assert!(lost_chunk_store.contains(&lost_digest));
+        assert_eq!(
+            first_manifest.entry(&file_path).unwrap().chunk_digests,
+            second_manifest.entry(&file_path).unwrap().chunk_digests
+        );
     }
 
-    return Ok(());
-}
+    #[test]
+    fn prune_backups_removes_stale_manifests_and_garbage_collects_unreferenced_chunks() {
+        let identity = x25519::Identity::generate();
+        let public_key = identity.to_public().to_string();
+
+        let backup_dir = temp_dir_root("prune-backups");
+        fs::create_dir_all(&backup_dir).unwrap();
+        let backup_folder_path = backup_dir.join("laptop-backup");
+
+        let chunk_store_path = temp_dir_root("prune-chunks");
+        let chunk_store = ChunkStore::open(&chunk_store_path).unwrap();
+
+        // Referenced only by the manifest that will be pruned away.
+        let stale_digest = blake3::hash(b"stale chunk");
+        chunk_store
+            .store(&stale_digest, CompressionCodec::None, b"stale chunk", &public_key, false)
+            .unwrap();
+
+        // Referenced by every manifest, so it must survive pruning.
+        let shared_digest = blake3::hash(b"shared chunk");
+        chunk_store
+            .store(&shared_digest, CompressionCodec::None, b"shared chunk", &public_key, false)
+            .unwrap();
+
+        let fingerprint = FileFingerprint {
+            size: 0,
+            mtime_nanos: 0,
+            ctime_nanos: 0,
+        };
+
+        let mut old_manifest = Manifest::new();
+        old_manifest.record(
+            PathBuf::from("/a.txt"),
+            FileEntry {
+                fingerprint,
+                mode: 0o100644,
+                chunk_digests: vec![*stale_digest.as_bytes(), *shared_digest.as_bytes()],
+            },
+        );
+        old_manifest
+            .save(format!("{}-2020-01-01_00-00-00.manifest", backup_folder_path.display()), &public_key)
+            .unwrap();
+
+        let mut new_manifest = Manifest::new();
+        new_manifest.record(
+            PathBuf::from("/a.txt"),
+            FileEntry {
+                fingerprint,
+                mode: 0o100644,
+                chunk_digests: vec![*shared_digest.as_bytes()],
+            },
+        );
+        new_manifest
+            .save(format!("{}-2020-01-02_00-00-00.manifest", backup_folder_path.display()), &public_key)
+            .unwrap();
 
-fn create_folder_in_backup_structure(
-    source_path_folder: impl AsRef<Path>,
-    backup_folder_path: impl AsRef<Path>,
-) -> Result<PathBuf> {
-    let source_path_folder: PathBuf = PathBuf::from(source_path_folder.as_ref());
-    let backup_folder_path: PathBuf = PathBuf::from(backup_folder_path.as_ref());
+        let report = prune_backups(&backup_folder_path, &chunk_store_path, &identity, 1).unwrap();
 
-    let relative_source_path: PathBuf = PathBuf::from(
-        source_path_folder
-            .strip_prefix(Component::RootDir)
-            .unwrap_or(&source_path_folder), // unwrap_or returns the default value if the strip_prefix was not able to remove any RootDir component.
-    );
+        assert_eq!(report.removed_manifests, 1);
+        assert_eq!(report.removed_chunks, 1);
+        assert!(!chunk_store.contains(&stale_digest));
+        assert!(chunk_store.contains(&shared_digest));
+    }
 
-    let full_backup_folder_path: PathBuf = backup_folder_path.join(relative_source_path);
+    #[test]
+    fn sanitize_entry_path_accepts_normal_relative_paths() {
+        let sanitized = sanitize_entry_path(Path::new("home/user/notes.txt")).unwrap();
+        assert_eq!(sanitized, Path::new("home/user/notes.txt"));
+    }
 
-    fs::create_dir_all(&full_backup_folder_path)?;
+    #[test]
+    fn sanitize_entry_path_rejects_parent_dir_components() {
+        assert!(sanitize_entry_path(Path::new("../etc/passwd")).is_err());
+        assert!(sanitize_entry_path(Path::new("home/../../etc/passwd")).is_err());
+    }
 
-    return Ok(full_backup_folder_path);
+    #[test]
+    fn sanitize_entry_path_rejects_absolute_paths() {
+        assert!(sanitize_entry_path(Path::new("/etc/passwd")).is_err());
+    }
 }
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use age::{Decryptor, Encryptor, x25519};
+use anyhow::{Context, Result, bail};
+
+use crate::compression::CompressionCodec;
+
+/// A content-addressed directory of individually age-encrypted chunks, keyed by their
+/// BLAKE3 digest. Chunks are fanned out one level deep by the first two hex digits of
+/// their digest so the directory doesn't end up with millions of flat siblings.
+pub struct ChunkStore {
+    root: PathBuf,
+}
+
+impl ChunkStore {
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root: PathBuf = root.as_ref().to_path_buf();
+
+        fs::create_dir_all(&root)
+            .with_context(|| format!("failed to create chunk store {}", root.display()))?;
+
+        return Ok(ChunkStore { root });
+    }
+
+    fn chunk_path(&self, digest: &blake3::Hash) -> PathBuf {
+        let hex: String = digest.to_hex().to_string();
+        self.root.join(&hex[..2]).join(&hex[2..])
+    }
+
+    pub fn contains(&self, digest: &blake3::Hash) -> bool {
+        self.chunk_path(digest).is_file()
+    }
+
+    /// Encrypts and stores `data` under its digest, tagging it with the codec it was
+    /// compressed with. Unless `force` is set, a digest that's already present is left
+    /// untouched and not re-encrypted — chunks are deduplicated across runs, so the
+    /// codec recorded here may not match the current run's `--compression` choice, and
+    /// that's fine: [`ChunkStore::load`] returns whatever codec the chunk was actually
+    /// stored with, rather than relying on a caller to guess correctly.
+    pub fn store(
+        &self,
+        digest: &blake3::Hash,
+        codec: CompressionCodec,
+        data: &[u8],
+        public_key: &str,
+        force: bool,
+    ) -> Result<()> {
+        if !force && self.contains(digest) {
+            return Ok(());
+        }
+
+        let chunk_path: PathBuf = self.chunk_path(digest);
+
+        if let Some(parent) = chunk_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+
+        let recipient = x25519::Recipient::from_str(public_key)
+            .map_err(|e| anyhow::anyhow!("invalid age recipient \"{public_key}\": {e}"))?;
+
+        let encryptor = Encryptor::with_recipients(vec![Box::new(recipient) as Box<dyn age::Recipient + Send>])
+            .expect("recipient vec is non-empty");
+
+        let output_file = BufWriter::new(
+            File::create(&chunk_path)
+                .with_context(|| format!("failed to create chunk {}", chunk_path.display()))?,
+        );
+
+        let mut encrypted_output = encryptor
+            .wrap_output(output_file)
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+        encrypted_output.write_all(&[codec.to_byte()])?;
+        encrypted_output.write_all(data)?;
+        encrypted_output.finish()?;
+
+        return Ok(());
+    }
+
+    /// Decrypts the chunk stored under `digest`, returning the codec it was compressed
+    /// with alongside its (still compressed) bytes.
+    pub fn load(&self, digest: &blake3::Hash, identity: &x25519::Identity) -> Result<(CompressionCodec, Vec<u8>)> {
+        let chunk_path: PathBuf = self.chunk_path(digest);
+
+        let input_file = BufReader::new(
+            File::open(&chunk_path)
+                .with_context(|| format!("missing chunk {}", chunk_path.display()))?,
+        );
+
+        let decryptor = Decryptor::new(input_file).map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+        let Decryptor::Recipients(decryptor) = decryptor else {
+            bail!("chunk {} is not recipient-encrypted", chunk_path.display());
+        };
+
+        let mut decrypted = decryptor
+            .decrypt(std::iter::once(identity as &dyn age::Identity))
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+        let mut codec_byte = [0u8; 1];
+        decrypted
+            .read_exact(&mut codec_byte)
+            .with_context(|| format!("chunk {} is truncated", chunk_path.display()))?;
+        let codec = CompressionCodec::from_byte(codec_byte[0])
+            .with_context(|| format!("chunk {} has an unrecognized codec tag", chunk_path.display()))?;
+
+        let mut data: Vec<u8> = Vec::new();
+        decrypted.read_to_end(&mut data)?;
+
+        return Ok((codec, data));
+    }
+
+    /// Deletes every stored chunk whose digest is not in `referenced`, returning how
+    /// many chunks were removed.
+    pub fn garbage_collect(&self, referenced: &HashSet<blake3::Hash>) -> Result<usize> {
+        let mut removed: usize = 0;
+
+        for fanout_entry in fs::read_dir(&self.root)
+            .with_context(|| format!("failed to list chunk store {}", self.root.display()))?
+        {
+            let fanout_entry = fanout_entry?;
+
+            if !fanout_entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let Some(fanout_prefix) = fanout_entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+
+            for chunk_entry in fs::read_dir(fanout_entry.path())? {
+                let chunk_entry = chunk_entry?;
+                let Some(suffix) = chunk_entry.file_name().to_str().map(str::to_owned) else {
+                    continue;
+                };
+
+                let Ok(digest) = blake3::Hash::from_hex(format!("{fanout_prefix}{suffix}")) else {
+                    continue;
+                };
+
+                if !referenced.contains(&digest) {
+                    fs::remove_file(chunk_entry.path())?;
+                    removed += 1;
+                }
+            }
+        }
+
+        return Ok(removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read as _;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use age::x25519;
+
+    use super::*;
+    use crate::chunker::ContentDefinedChunker;
+    use crate::compression::{CompressionReader, CompressionWriter};
+
+    /// Each test gets its own chunk store directory under the system temp dir so
+    /// concurrent test runs can't collide.
+    fn temp_chunk_store_root() -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(format!("laptop-backup-chunk-store-test-{}-{n}", std::process::id()))
+    }
+
+    #[test]
+    fn chunk_store_round_trips_a_chunked_and_compressed_file() {
+        let identity = x25519::Identity::generate();
+        let public_key = identity.to_public().to_string();
+
+        let store = ChunkStore::open(temp_chunk_store_root()).unwrap();
+
+        // Large enough, and varied enough, to force the content-defined chunker to
+        // cut more than one chunk.
+        let original: Vec<u8> = (0..5_000_000u32).flat_map(|n| n.to_le_bytes()).collect();
+
+        let mut chunker = ContentDefinedChunker::new(original.as_slice());
+        let mut stored_digests: Vec<blake3::Hash> = Vec::new();
+
+        while let Some(chunk) = chunker.next_chunk().unwrap() {
+            let digest = blake3::hash(&chunk);
+
+            let mut compressor = CompressionWriter::new(CompressionCodec::Zstd, Vec::new()).unwrap();
+            compressor.write_all(&chunk).unwrap();
+            let compressed_chunk = compressor.finish().unwrap();
+
+            store
+                .store(&digest, CompressionCodec::Zstd, &compressed_chunk, &public_key, false)
+                .unwrap();
+            stored_digests.push(digest);
+        }
+
+        assert!(stored_digests.len() > 1, "test input should cut into multiple chunks");
+
+        let mut reassembled: Vec<u8> = Vec::new();
+
+        for digest in &stored_digests {
+            let (codec, compressed_chunk) = store.load(digest, &identity).unwrap();
+            assert_eq!(codec, CompressionCodec::Zstd);
+
+            let mut decompressed_chunk: Vec<u8> = Vec::new();
+            CompressionReader::new(codec, compressed_chunk.as_slice())
+                .unwrap()
+                .read_to_end(&mut decompressed_chunk)
+                .unwrap();
+
+            reassembled.extend_from_slice(&decompressed_chunk);
+        }
+
+        assert_eq!(reassembled, original);
+    }
+}
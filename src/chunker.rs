@@ -0,0 +1,83 @@
+use std::io::{self, Read};
+
+/// Chunk boundaries are never placed closer together than this...
+const MIN_CHUNK_SIZE: usize = 1 << 20; // 1 MiB
+/// ...nor ever let a chunk grow past this, so a pathological input (e.g. an
+/// incompressible stream that never rolls a boundary) can't produce one giant chunk.
+const MAX_CHUNK_SIZE: usize = 4 << 20; // 4 MiB
+
+/// Masking the low bits of the rolling hash against this value gives a ~2 MiB average
+/// chunk size: a boundary is cut whenever the masked bits are all zero, which happens
+/// with probability `1 / (MASK + 1)`.
+const BOUNDARY_MASK: u64 = (1 << 21) - 1;
+
+/// A random 64-bit word per input byte value, used by the gear hash below. The 64-bit
+/// shift register naturally "forgets" bytes older than about 64 shifts, giving the
+/// rolling hash an effective window close to the ~64-byte window classic Rabin/gear
+/// chunkers use, without having to maintain an explicit ring buffer.
+const GEAR_TABLE: [u64; 256] = build_gear_table();
+
+const fn build_gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut seed: u64 = 0x9E3779B97F4A7C15;
+    let mut i = 0;
+
+    while i < 256 {
+        seed = seed.wrapping_add(0x9E3779B97F4A7C15);
+        let mut mixed = seed;
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D049BB133111EB);
+        mixed ^= mixed >> 31;
+        table[i] = mixed;
+        i += 1;
+    }
+
+    table
+}
+
+/// Splits a byte stream into content-defined chunks with a gear-hash rolling boundary.
+pub struct ContentDefinedChunker<R: Read> {
+    reader: R,
+    eof: bool,
+}
+
+impl<R: Read> ContentDefinedChunker<R> {
+    pub fn new(reader: R) -> Self {
+        ContentDefinedChunker { reader, eof: false }
+    }
+
+    /// Reads and returns the next chunk, or `None` once the stream is exhausted.
+    pub fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.eof {
+            return Ok(None);
+        }
+
+        let mut chunk: Vec<u8> = Vec::with_capacity(MIN_CHUNK_SIZE);
+        let mut hash: u64 = 0;
+        let mut byte = [0u8; 1];
+
+        loop {
+            if self.reader.read(&mut byte)? == 0 {
+                self.eof = true;
+                break;
+            }
+
+            chunk.push(byte[0]);
+            hash = (hash << 1).wrapping_add(GEAR_TABLE[byte[0] as usize]);
+
+            if chunk.len() >= MAX_CHUNK_SIZE {
+                break;
+            }
+
+            if chunk.len() >= MIN_CHUNK_SIZE && hash & BOUNDARY_MASK == 0 {
+                break;
+            }
+        }
+
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+
+        return Ok(Some(chunk));
+    }
+}
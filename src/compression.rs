@@ -0,0 +1,127 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// The compression codec applied to each chunk before it reaches the age encryptor.
+/// `None` is the default for compatibility with older backups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+pub enum CompressionCodec {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl fmt::Display for CompressionCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CompressionCodec::None => "none",
+            CompressionCodec::Zstd => "zstd",
+            CompressionCodec::Lz4 => "lz4",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl CompressionCodec {
+    /// A single-byte tag stored alongside each chunk so the codec it was compressed
+    /// with travels with the chunk itself, rather than living in the manifest. Chunks
+    /// are deduplicated across runs, so the codec a chunk was actually stored with can
+    /// differ from whatever codec the current run was invoked with.
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            CompressionCodec::None => 0,
+            CompressionCodec::Zstd => 1,
+            CompressionCodec::Lz4 => 2,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Result<Self> {
+        Ok(match byte {
+            0 => CompressionCodec::None,
+            1 => CompressionCodec::Zstd,
+            2 => CompressionCodec::Lz4,
+            other => anyhow::bail!("unknown compression codec tag {other}"),
+        })
+    }
+}
+
+/// A [`Write`] adapter that sits between the chunker and the age encryptor,
+/// compressing each chunk before it's encrypted (encrypting raw chunks
+/// leaves it incompressible afterward, so compression has to happen first).
+pub enum CompressionWriter<W: Write> {
+    None(W),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+}
+
+impl<W: Write> CompressionWriter<W> {
+    pub fn new(codec: CompressionCodec, inner: W) -> Result<Self> {
+        return Ok(match codec {
+            CompressionCodec::None => CompressionWriter::None(inner),
+            CompressionCodec::Zstd => {
+                CompressionWriter::Zstd(zstd::stream::write::Encoder::new(inner, 0)?)
+            }
+            CompressionCodec::Lz4 => CompressionWriter::Lz4(lz4_flex::frame::FrameEncoder::new(inner)),
+        });
+    }
+
+    /// Flushes any buffered compressed data and hands back the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        return match self {
+            CompressionWriter::None(inner) => Ok(inner),
+            CompressionWriter::Zstd(encoder) => Ok(encoder.finish()?),
+            CompressionWriter::Lz4(encoder) => Ok(encoder.finish()?),
+        };
+    }
+}
+
+impl<W: Write> Write for CompressionWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return match self {
+            CompressionWriter::None(inner) => inner.write(buf),
+            CompressionWriter::Zstd(encoder) => encoder.write(buf),
+            CompressionWriter::Lz4(encoder) => encoder.write(buf),
+        };
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return match self {
+            CompressionWriter::None(inner) => inner.flush(),
+            CompressionWriter::Zstd(encoder) => encoder.flush(),
+            CompressionWriter::Lz4(encoder) => encoder.flush(),
+        };
+    }
+}
+
+/// The read-side counterpart of [`CompressionWriter`], decompressing each chunk
+/// after the age decryptor and before its bytes are written out during restore.
+pub enum CompressionReader<R: Read> {
+    None(R),
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<R>>),
+    Lz4(lz4_flex::frame::FrameDecoder<R>),
+}
+
+impl<R: Read> CompressionReader<R> {
+    pub fn new(codec: CompressionCodec, inner: R) -> Result<Self> {
+        return Ok(match codec {
+            CompressionCodec::None => CompressionReader::None(inner),
+            CompressionCodec::Zstd => CompressionReader::Zstd(zstd::stream::read::Decoder::new(inner)?),
+            CompressionCodec::Lz4 => CompressionReader::Lz4(lz4_flex::frame::FrameDecoder::new(inner)),
+        });
+    }
+}
+
+impl<R: Read> Read for CompressionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return match self {
+            CompressionReader::None(inner) => inner.read(buf),
+            CompressionReader::Zstd(decoder) => decoder.read(buf),
+            CompressionReader::Lz4(decoder) => decoder.read(buf),
+        };
+    }
+}
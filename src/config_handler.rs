@@ -1,6 +1,9 @@
-use anyhow::{Context, Result};
+use std::str::FromStr;
 use std::{fs, path::Path};
 
+use age::x25519;
+use anyhow::{Context, Result};
+
 pub fn load_paths_from_file(paths_file: impl AsRef<Path>) -> Result<Vec<String>> {
     let paths_file: &Path = paths_file.as_ref();
 
@@ -24,3 +27,15 @@ pub fn load_public_key_from_file(public_key_file: &str) -> Result<String> {
 
     return Ok(public_key);
 }
+
+pub fn load_secret_key_from_file(secret_key_file: impl AsRef<Path>) -> Result<x25519::Identity> {
+    let secret_key_file: &Path = secret_key_file.as_ref();
+
+    let secret_key: String = fs::read_to_string(secret_key_file)
+        .with_context(|| format!("could not read secret key from {:?}", secret_key_file))?;
+
+    let identity = x25519::Identity::from_str(secret_key.trim())
+        .map_err(|e| anyhow::anyhow!("invalid age identity in {:?}: {e}", secret_key_file))?;
+
+    return Ok(identity);
+}
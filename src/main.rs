@@ -1,29 +1,240 @@
+// This crate generally prefers an explicit `return` at the end of a function body
+// over relying on tail-expression return.
+#![allow(clippy::needless_return)]
+
 mod backup_library;
+mod chunk_store;
+mod chunker;
+mod compression;
+mod config_handler;
+mod manifest;
+
+use std::path::PathBuf;
+
+use age::x25519;
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+
+use crate::backup_library::{BackupOptions, create_chunked_backup, prune_backups, restore_chunked_backup};
+use crate::compression::CompressionCodec;
+use crate::config_handler::{load_paths_from_file, load_public_key_from_file, load_secret_key_from_file};
+
+/// Encrypted, incremental, content-addressed backups for a single machine.
+#[derive(Parser)]
+#[command(name = "laptop-backup")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Chunk, deduplicate, and encrypt every path in the paths file into the backup
+    Backup(BackupArgs),
+    /// Decrypt a backup's manifest and rebuild its files under a destination directory
+    Restore(RestoreArgs),
+    /// Delete old manifests and garbage-collect chunks no longer referenced by any
+    Prune(PruneArgs),
+}
 
-use crate::backup_library::{
-    backup_directory_contents, encrypt_file, load_paths_from_file, load_public_key_from_file,
-    zip_files_in_folder,
-};
+#[derive(Args)]
+struct BackupArgs {
+    /// File listing the absolute paths to back up, one per line
+    #[arg(long, default_value = "paths.txt")]
+    paths_file: PathBuf,
 
-static PATHS_FILE: &str = "paths.txt";
-static PUBLIC_KEY_FILE: &str = "public_key.txt";
-static BACKUP_FOLDER_PATH: &str = "laptop-backup";
+    /// age recipient (public key) to encrypt for
+    #[arg(long, conflicts_with = "recipient_file")]
+    recipient: Option<String>,
+
+    /// File containing the age recipient (public key) to encrypt for
+    #[arg(long, conflicts_with = "recipient")]
+    recipient_file: Option<PathBuf>,
+
+    /// Name backups and manifests are written under
+    #[arg(long, default_value = "laptop-backup")]
+    output: PathBuf,
+
+    /// Directory the content-addressed chunk store lives in
+    #[arg(long, default_value = "laptop-backup-chunks")]
+    chunk_store: PathBuf,
+
+    /// Re-chunk and re-store every file, bypassing the chunk store's dedup
+    #[arg(long)]
+    full: bool,
+
+    /// Don't descend into directories on a different filesystem than their source path
+    #[arg(long)]
+    one_file_system: bool,
+
+    /// Compression codec to apply to chunks before encryption
+    #[arg(long, value_enum, default_value_t = CompressionCodec::None)]
+    compression: CompressionCodec,
+
+    /// age identity (secret key), optionally provided so unchanged files can be
+    /// skipped by comparing against the most recent manifest instead of always
+    /// re-chunking
+    #[arg(long, conflicts_with = "identity_file")]
+    identity: Option<String>,
+
+    /// File containing the age identity (secret key)
+    #[arg(long, conflicts_with = "identity")]
+    identity_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct RestoreArgs {
+    /// The manifest (backup index) to restore
+    manifest: PathBuf,
+
+    /// Directory the content-addressed chunk store lives in
+    #[arg(long, default_value = "laptop-backup-chunks")]
+    chunk_store: PathBuf,
+
+    /// age identity (secret key) to decrypt with
+    #[arg(long, conflicts_with = "identity_file")]
+    identity: Option<String>,
+
+    /// File containing the age identity (secret key) to decrypt with
+    #[arg(long, conflicts_with = "identity")]
+    identity_file: Option<PathBuf>,
+
+    /// Directory to restore files into
+    #[arg(long)]
+    destination: PathBuf,
+
+    /// Only restore entries whose original path starts with this prefix
+    #[arg(long)]
+    path_prefix: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct PruneArgs {
+    /// Name backups and manifests were written under
+    #[arg(long, default_value = "laptop-backup")]
+    output: PathBuf,
+
+    /// Directory the content-addressed chunk store lives in
+    #[arg(long, default_value = "laptop-backup-chunks")]
+    chunk_store: PathBuf,
+
+    /// age identity (secret key), needed to read manifests while garbage-collecting
+    #[arg(long, conflicts_with = "identity_file")]
+    identity: Option<String>,
+
+    /// File containing the age identity (secret key)
+    #[arg(long, conflicts_with = "identity")]
+    identity_file: Option<PathBuf>,
+
+    /// How many of the most recent manifests to keep
+    #[arg(long, default_value_t = 7)]
+    keep: usize,
+}
 
 fn main() {
-    let paths: Vec<String> = load_paths_from_file(PATHS_FILE).expect("failed to load path file");
-    let public_encryption_key: String =
-        load_public_key_from_file(PUBLIC_KEY_FILE).expect("failed to load public key");
-
-    for path in paths {
-        if let Err(err) = backup_directory_contents(path, BACKUP_FOLDER_PATH) {
-            eprintln!("Error retrieving file/folder: {:?}", err);
-        }
-    }
+    let cli = Cli::parse();
 
-    let archive_file: std::path::PathBuf = zip_files_in_folder(BACKUP_FOLDER_PATH).unwrap(); // TODO: handle error
+    let result = match cli.command {
+        Command::Backup(args) => run_backup(args),
+        Command::Restore(args) => run_restore(args),
+        Command::Prune(args) => run_prune(args),
+    };
 
-    if let Err(err) = encrypt_file(archive_file, public_encryption_key) {
-        eprintln!("Encryption failed: {err}");
+    if let Err(err) = result {
+        eprintln!("{err:?}");
         std::process::exit(1);
     }
 }
+
+fn run_backup(args: BackupArgs) -> Result<()> {
+    let paths: Vec<String> = load_paths_from_file(&args.paths_file)?;
+    let recipient: String = resolve_recipient(args.recipient, args.recipient_file)?;
+    let reference_identity: Option<x25519::Identity> =
+        resolve_optional_identity(args.identity, args.identity_file)?;
+
+    let options = BackupOptions {
+        public_key: recipient,
+        force: args.full,
+        compression_codec: args.compression,
+        one_file_system: args.one_file_system,
+    };
+
+    let manifest_path = create_chunked_backup(
+        &paths,
+        &args.output,
+        &args.chunk_store,
+        options,
+        reference_identity.as_ref(),
+    )?;
+
+    println!("wrote manifest {}", manifest_path.display());
+
+    return Ok(());
+}
+
+fn run_restore(args: RestoreArgs) -> Result<()> {
+    let identity: x25519::Identity = resolve_identity(args.identity, args.identity_file)?;
+
+    return restore_chunked_backup(
+        &args.manifest,
+        &args.chunk_store,
+        &identity,
+        &args.destination,
+        args.path_prefix.as_deref(),
+    );
+}
+
+fn run_prune(args: PruneArgs) -> Result<()> {
+    let identity: x25519::Identity = resolve_identity(args.identity, args.identity_file)?;
+
+    let report = prune_backups(&args.output, &args.chunk_store, &identity, args.keep)?;
+
+    println!(
+        "removed {} manifest(s) and {} chunk(s)",
+        report.removed_manifests, report.removed_chunks
+    );
+
+    return Ok(());
+}
+
+fn resolve_recipient(inline: Option<String>, file: Option<PathBuf>) -> Result<String> {
+    if let Some(recipient) = inline {
+        return Ok(recipient);
+    }
+
+    if let Some(recipient_file) = file {
+        return load_public_key_from_file(
+            recipient_file
+                .to_str()
+                .context("recipient file path is not valid UTF-8")?,
+        );
+    }
+
+    anyhow::bail!("one of --recipient or --recipient-file is required");
+}
+
+fn resolve_identity(inline: Option<String>, file: Option<PathBuf>) -> Result<x25519::Identity> {
+    use std::str::FromStr;
+
+    if let Some(identity) = inline {
+        return x25519::Identity::from_str(identity.trim())
+            .map_err(|e| anyhow::anyhow!("invalid age identity: {e}"));
+    }
+
+    if let Some(identity_file) = file {
+        return load_secret_key_from_file(identity_file);
+    }
+
+    anyhow::bail!("one of --identity or --identity-file is required");
+}
+
+/// Like [`resolve_identity`], but returns `None` when neither flag was given instead of
+/// erroring, since an identity is optional at backup time (it only enables skipping
+/// unchanged files against the most recent manifest).
+fn resolve_optional_identity(inline: Option<String>, file: Option<PathBuf>) -> Result<Option<x25519::Identity>> {
+    if inline.is_none() && file.is_none() {
+        return Ok(None);
+    }
+
+    return resolve_identity(inline, file).map(Some);
+}
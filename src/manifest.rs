@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use age::{Decryptor, Encryptor, x25519};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// Identifies a manifest file so unrelated or foreign files are rejected outright,
+/// mirroring zvault's `HEADER_STRING`/`HEADER_VERSION` scheme. Kept as a plaintext
+/// prefix ahead of the encrypted body so a quick look at a file can tell it's ours
+/// before paying for decryption.
+const HEADER_STRING: &[u8; 7] = b"LBKMNFT";
+const HEADER_VERSION: u8 = 4;
+
+/// The fingerprint of a single backed-up file, used to detect whether it changed
+/// since the reference manifest was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFingerprint {
+    pub size: u64,
+    pub mtime_nanos: i64,
+    pub ctime_nanos: i64,
+}
+
+impl FileFingerprint {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        FileFingerprint {
+            size: metadata.size(),
+            mtime_nanos: metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec(),
+            ctime_nanos: metadata.ctime() * 1_000_000_000 + metadata.ctime_nsec(),
+        }
+    }
+}
+
+/// A file's manifest entry: its fingerprint, permission bits, plus the ordered list of
+/// chunk digests (each a BLAKE3 hash) that reassemble it, as produced by the
+/// content-defined chunker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub fingerprint: FileFingerprint,
+    /// The file's mode (file-type and permission bits), as returned by
+    /// [`MetadataExt::mode`]; its permission bits are restored onto
+    /// the rebuilt file so e.g. a private key or executable script doesn't come back
+    /// with the restoring process's default umask permissions instead of its own.
+    pub mode: u32,
+    pub chunk_digests: Vec<[u8; 32]>,
+}
+
+/// The encrypted index of a single backup run: every file's metadata and the ordered
+/// list of chunk digests that reassemble it. Restore rebuilds each file by
+/// concatenating its chunks, in order, from the chunk store.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    files: HashMap<PathBuf, FileEntry>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Manifest::default()
+    }
+
+    pub fn record(&mut self, path: PathBuf, entry: FileEntry) {
+        self.files.insert(path, entry);
+    }
+
+    pub fn entry(&self, path: &Path) -> Option<&FileEntry> {
+        self.files.get(path)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&PathBuf, &FileEntry)> {
+        self.files.iter()
+    }
+
+    /// Decrypts and parses a manifest written by [`Manifest::save`].
+    pub fn load(manifest_path: impl AsRef<Path>, identity: &x25519::Identity) -> Result<Self> {
+        let manifest_path: &Path = manifest_path.as_ref();
+
+        let mut reader = BufReader::new(
+            File::open(manifest_path)
+                .with_context(|| format!("could not open manifest {}", manifest_path.display()))?,
+        );
+
+        let mut header = [0u8; HEADER_STRING.len()];
+        reader
+            .read_exact(&mut header)
+            .with_context(|| format!("manifest {} is truncated", manifest_path.display()))?;
+
+        if &header != HEADER_STRING {
+            bail!(
+                "{} is not a laptop-backup manifest",
+                manifest_path.display()
+            );
+        }
+
+        let mut version = [0u8; 1];
+        reader
+            .read_exact(&mut version)
+            .with_context(|| format!("manifest {} is truncated", manifest_path.display()))?;
+
+        if version[0] != HEADER_VERSION {
+            bail!(
+                "manifest {} has unsupported version {}",
+                manifest_path.display(),
+                version[0]
+            );
+        }
+
+        let decryptor = Decryptor::new(reader).map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+        let Decryptor::Recipients(decryptor) = decryptor else {
+            bail!(
+                "manifest {} is not recipient-encrypted",
+                manifest_path.display()
+            );
+        };
+
+        let mut decrypted = decryptor
+            .decrypt(std::iter::once(identity as &dyn age::Identity))
+            .map_err(|err| anyhow::anyhow!(err.to_string()))
+            .with_context(|| format!("failed to decrypt manifest {}", manifest_path.display()))?;
+
+        let manifest: Manifest = bincode::deserialize_from(&mut decrypted)
+            .with_context(|| format!("failed to parse manifest {}", manifest_path.display()))?;
+
+        return Ok(manifest);
+    }
+
+    /// Serializes and age-encrypts the manifest to `manifest_path`.
+    pub fn save(&self, manifest_path: impl AsRef<Path>, public_key: &str) -> Result<()> {
+        let manifest_path: &Path = manifest_path.as_ref();
+
+        let recipient = x25519::Recipient::from_str(public_key)
+            .map_err(|e| anyhow::anyhow!("invalid age recipient \"{public_key}\": {e}"))?;
+
+        let encryptor = Encryptor::with_recipients(vec![Box::new(recipient) as Box<dyn age::Recipient + Send>])
+            .expect("recipient vec is non-empty");
+
+        let mut writer = BufWriter::new(
+            File::create(manifest_path)
+                .with_context(|| format!("could not create manifest {}", manifest_path.display()))?,
+        );
+
+        writer.write_all(HEADER_STRING)?;
+        writer.write_all(&[HEADER_VERSION])?;
+
+        let mut encrypted_writer = encryptor
+            .wrap_output(writer)
+            .map_err(|err| anyhow::anyhow!(err.to_string()))?;
+
+        bincode::serialize_into(&mut encrypted_writer, self)
+            .with_context(|| format!("failed to write manifest {}", manifest_path.display()))?;
+
+        encrypted_writer.finish()?;
+
+        return Ok(());
+    }
+}
+
+/// Lists every manifest next to `backup_folder_path`, oldest first. Timestamps are
+/// formatted as `%Y-%m-%d_%H-%M-%S`, so lexicographic order is chronological order.
+pub fn list_manifests(backup_folder_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let backup_folder_path: &Path = backup_folder_path.as_ref();
+
+    let Some(file_name) = backup_folder_path.file_name().and_then(|n| n.to_str()) else {
+        bail!(
+            "internal error extracting file name from {}",
+            backup_folder_path.display()
+        );
+    };
+
+    let search_dir: &Path = backup_folder_path.parent().unwrap_or_else(|| Path::new("."));
+    let prefix: String = format!("{file_name}-");
+
+    let mut manifests: Vec<PathBuf> = Vec::new();
+
+    for entry in fs::read_dir(search_dir)
+        .with_context(|| format!("could not list directory {}", search_dir.display()))?
+    {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+
+        if name.starts_with(&prefix) && name.ends_with(".manifest") {
+            manifests.push(entry.path());
+        }
+    }
+
+    manifests.sort();
+
+    return Ok(manifests);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use age::x25519;
+
+    use super::*;
+
+    /// Each test gets its own manifest path under the system temp dir so concurrent
+    /// test runs can't collide.
+    fn temp_manifest_path() -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(format!("laptop-backup-manifest-test-{}-{n}", std::process::id()))
+    }
+
+    #[test]
+    fn manifest_round_trips_through_save_and_load() {
+        let identity = x25519::Identity::generate();
+        let public_key = identity.to_public().to_string();
+
+        let mut manifest = Manifest::new();
+        manifest.record(
+            PathBuf::from("/home/user/notes.txt"),
+            FileEntry {
+                fingerprint: FileFingerprint {
+                    size: 42,
+                    mtime_nanos: 1,
+                    ctime_nanos: 2,
+                },
+                mode: 0o100644,
+                chunk_digests: vec![[7u8; 32], [9u8; 32]],
+            },
+        );
+
+        let manifest_path = temp_manifest_path();
+        manifest.save(&manifest_path, &public_key).unwrap();
+
+        let loaded = Manifest::load(&manifest_path, &identity).unwrap();
+        let entry = loaded.entry(Path::new("/home/user/notes.txt")).unwrap();
+
+        assert_eq!(entry.fingerprint, manifest.entry(Path::new("/home/user/notes.txt")).unwrap().fingerprint);
+        assert_eq!(entry.mode, 0o100644);
+        assert_eq!(entry.chunk_digests, vec![[7u8; 32], [9u8; 32]]);
+    }
+
+    #[test]
+    fn manifest_load_rejects_a_foreign_file() {
+        let identity = x25519::Identity::generate();
+
+        let manifest_path = temp_manifest_path();
+        fs::write(&manifest_path, b"not a manifest at all").unwrap();
+
+        assert!(Manifest::load(&manifest_path, &identity).is_err());
+    }
+}